@@ -0,0 +1,343 @@
+//! Headless backend that scans out directly to a KMS connector and reads
+//! input from `libinput`, for running raven on a bare TTY or as a kiosk.
+use std::io::Read;
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, RawFd};
+use std::time::{Duration, Instant};
+
+use drm::control::{connector, Device as ControlDevice, Event as DrmEvent};
+use drm::Device as BasicDevice;
+use input::event::keyboard::KeyboardEventTrait;
+use input::event::pointer::PointerEvent;
+use input::{Libinput, LibinputInterface};
+use signal_hook::consts::{SIGUSR1, SIGUSR2};
+use signal_hook::iterator::Signals;
+
+use uxn::Uxn;
+use varvara::{Key, MouseState, Varvara};
+
+/// Minimal `open`/`close` shim so `libinput` can use the udev seat without
+/// pulling in a full privileged session manager.
+struct Interface;
+
+impl LibinputInterface for Interface {
+    fn open_restricted(
+        &mut self,
+        path: &std::path::Path,
+        flags: i32,
+    ) -> Result<std::os::unix::io::OwnedFd, i32> {
+        use std::os::unix::fs::OpenOptionsExt;
+        std::fs::OpenOptions::new()
+            .custom_flags(flags)
+            .read(true)
+            .write(true)
+            .open(path)
+            .map(|f| f.into())
+            .map_err(|e| e.raw_os_error().unwrap_or(libc::EIO))
+    }
+
+    fn close_restricted(&mut self, fd: std::os::unix::io::OwnedFd) {
+        drop(fd);
+    }
+}
+
+/// Thin wrapper around an open DRM device file, required by the `drm` crate
+/// to implement its `Device` traits.
+struct Card(std::fs::File);
+
+impl AsFd for Card {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.0.as_fd()
+    }
+}
+
+impl BasicDevice for Card {}
+impl ControlDevice for Card {}
+
+/// Runs raven against the given VM and device set using a direct KMS
+/// scanout buffer instead of a windowing compositor.
+///
+/// Picks the first connected connector and its preferred mode, creates a
+/// dumb buffer sized to that mode, and page-flips at the mode's refresh
+/// rate, gated by a `next_frame` deadline the way the `eframe` backend
+/// gates its redraws. Input is read from a `libinput` context bound to the
+/// current udev seat. Blocks between frames on `poll(2)` (input fd + DRM
+/// fd) rather than spinning.
+///
+/// DRM master is released on `SIGUSR1` and re-acquired on `SIGUSR2`. This
+/// function does not put the VT into `VT_PROCESS` mode, so those signals
+/// only arrive around a VT switch if that mode was set up elsewhere.
+pub fn run(mut vm: Uxn, mut dev: Varvara) -> anyhow::Result<()> {
+    let card = Card(
+        std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open("/dev/dri/card0")?,
+    );
+
+    let res = card.resource_handles()?;
+    let conn = res
+        .connectors()
+        .iter()
+        .filter_map(|&h| card.get_connector(h, false).ok())
+        .find(|c| c.state() == connector::State::Connected)
+        .ok_or_else(|| anyhow::anyhow!("no connected display found"))?;
+    let mode = *conn
+        .modes()
+        .iter()
+        .find(|m| m.mode_type().contains(drm::control::ModeTypeFlags::PREFERRED))
+        .or_else(|| conn.modes().first())
+        .ok_or_else(|| anyhow::anyhow!("connector has no modes"))?;
+
+    let (width, height) = mode.size();
+    let mut db = card.create_dumb_buffer(
+        (width.into(), height.into()),
+        drm::buffer::DrmFourcc::Xrgb8888,
+        32,
+    )?;
+    let fb = card.add_framebuffer(&db, 24, 32)?;
+
+    let crtc = res
+        .crtcs()
+        .first()
+        .copied()
+        .ok_or_else(|| anyhow::anyhow!("no CRTC available"))?;
+    card.set_crtc(crtc, Some(fb), (0, 0), &[conn.handle()], Some(mode))?;
+
+    let mut input_ctx = Libinput::new_with_udev(Interface);
+    input_ctx.udev_assign_seat("seat0").map_err(|_| {
+        anyhow::anyhow!("could not assign libinput to seat0")
+    })?;
+
+    // SIGUSR1/SIGUSR2 are the conventional VT_PROCESS release/acquire
+    // signals, sent by the kernel around a VT switch once the VT is in
+    // that mode; see `release_master`/`acquire_master`.
+    let mut vt_signals = Signals::new([SIGUSR1, SIGUSR2])?;
+    let mut has_master = true;
+
+    let refresh_hz = mode.vrefresh().max(1) as f64;
+    let frame_time = Duration::from_secs_f64(1.0 / refresh_hz);
+    let mut next_frame = Instant::now();
+    let mut flip_pending = false;
+
+    let mut pointer = PointerState::new(width, height);
+
+    loop {
+        for sig in vt_signals.pending() {
+            match sig {
+                SIGUSR1 => {
+                    release_master(&card)?;
+                    has_master = false;
+                }
+                SIGUSR2 => {
+                    acquire_master(&card)?;
+                    card.set_crtc(crtc, Some(fb), (0, 0), &[conn.handle()], Some(mode))?;
+                    has_master = true;
+                    // Force a fresh flip: the buffer we were scanning out
+                    // may be stale after switching back.
+                    next_frame = Instant::now();
+                }
+                _ => unreachable!(),
+            }
+        }
+
+        // Without master, or with a flip still outstanding, no frame can
+        // be drawn yet, so don't let an already-passed deadline turn the
+        // wait into a zero-timeout spin.
+        let timeout = if has_master && !flip_pending {
+            next_frame.saturating_duration_since(Instant::now())
+        } else {
+            frame_time
+        };
+        let mut fds = vec![input_ctx.as_raw_fd()];
+        if flip_pending {
+            fds.push(card.as_fd().as_raw_fd());
+        }
+        wait_for_readable(&fds, timeout)?;
+
+        input_ctx.dispatch()?;
+        for event in &mut input_ctx {
+            handle_input_event(event, &mut dev, &mut vm, &mut pointer);
+        }
+        dev.mouse(&mut vm, pointer.take());
+
+        // A page flip requested with `PageFlipFlags::EVENT` queues one
+        // completion event per CRTC; draining it here is required before
+        // the next `page_flip` call, or it eventually fails with EBUSY.
+        if flip_pending {
+            for event in card.receive_events()? {
+                if let DrmEvent::PageFlip(_) = event {
+                    flip_pending = false;
+                }
+            }
+        }
+
+        if has_master && !flip_pending && Instant::now() >= next_frame {
+            next_frame += frame_time;
+            dev.redraw(&mut vm);
+
+            let mut map = card.map_dumb_buffer(&mut db)?;
+            let out = dev.output(&vm);
+            map.as_mut().copy_from_slice(&out.frame);
+
+            card.page_flip(crtc, fb, drm::control::PageFlipFlags::EVENT, None)?;
+            flip_pending = true;
+            out.check()?;
+        }
+    }
+}
+
+/// Blocks until one of `fds` is readable or `timeout` elapses, so the main
+/// loop sleeps between frames instead of spinning when there's nothing to
+/// do.
+fn wait_for_readable(fds: &[RawFd], timeout: Duration) -> std::io::Result<()> {
+    let mut pollfds: Vec<libc::pollfd> = fds
+        .iter()
+        .map(|&fd| libc::pollfd { fd, events: libc::POLLIN, revents: 0 })
+        .collect();
+    let timeout_ms = timeout.as_millis().min(i32::MAX as u128) as i32;
+    let ret = unsafe {
+        libc::poll(pollfds.as_mut_ptr(), pollfds.len() as libc::nfds_t, timeout_ms)
+    };
+    if ret < 0 {
+        let err = std::io::Error::last_os_error();
+        // A VT-switch signal interrupting the wait is expected; `run`
+        // picks it up at the top of its loop.
+        if err.kind() != std::io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
+    Ok(())
+}
+
+/// Accumulates pointer state between `MouseState` updates, since
+/// `libinput` reports motion/buttons/scroll as separate events but
+/// `Varvara::mouse` wants one snapshot at a time.
+struct PointerState {
+    pos: (f32, f32),
+    bounds: (f32, f32),
+    buttons: u8,
+    scroll: (f32, f32),
+}
+
+impl PointerState {
+    fn new(width: u16, height: u16) -> PointerState {
+        PointerState {
+            pos: (f32::from(width) / 2.0, f32::from(height) / 2.0),
+            bounds: (f32::from(width), f32::from(height)),
+            buttons: 0,
+            scroll: (0.0, 0.0),
+        }
+    }
+
+    /// Returns a `MouseState` snapshot and resets the scroll delta, the
+    /// same way `Stage::update` drains `self.scroll` each frame.
+    fn take(&mut self) -> MouseState {
+        MouseState {
+            pos: self.pos,
+            scroll: std::mem::take(&mut self.scroll),
+            buttons: self.buttons,
+        }
+    }
+}
+
+/// Bit position of each mouse button within `MouseState::buttons`,
+/// matching the `eframe` backend's `[Primary, Middle, Secondary]` order.
+fn button_bit(code: u32) -> Option<u8> {
+    const BTN_LEFT: u32 = 0x110;
+    const BTN_RIGHT: u32 = 0x111;
+    const BTN_MIDDLE: u32 = 0x112;
+    match code {
+        BTN_LEFT => Some(0),
+        BTN_MIDDLE => Some(1),
+        BTN_RIGHT => Some(2),
+        _ => None,
+    }
+}
+
+fn handle_input_event(
+    event: input::Event,
+    dev: &mut Varvara,
+    vm: &mut Uxn,
+    pointer: &mut PointerState,
+) {
+    use input::event::Event;
+    use input::event::pointer::{Axis, ButtonState};
+    match event {
+        Event::Keyboard(k) => {
+            if let Some(key) = decode_keycode(k.key()) {
+                match k.key_state() {
+                    input::event::keyboard::KeyState::Pressed => {
+                        dev.pressed(vm, key)
+                    }
+                    input::event::keyboard::KeyState::Released => {
+                        dev.released(vm, key)
+                    }
+                }
+            }
+        }
+        Event::Pointer(PointerEvent::Motion(m)) => {
+            pointer.pos.0 =
+                (pointer.pos.0 + m.dx() as f32).clamp(0.0, pointer.bounds.0);
+            pointer.pos.1 =
+                (pointer.pos.1 + m.dy() as f32).clamp(0.0, pointer.bounds.1);
+        }
+        Event::Pointer(PointerEvent::Button(b)) => {
+            if let Some(bit) = button_bit(b.button()) {
+                match b.button_state() {
+                    ButtonState::Pressed => pointer.buttons |= 1 << bit,
+                    ButtonState::Released => pointer.buttons &= !(1 << bit),
+                }
+            }
+        }
+        Event::Pointer(PointerEvent::ScrollWheel(s)) => {
+            if s.has_axis(Axis::Horizontal) {
+                pointer.scroll.0 += s.scroll_value(Axis::Horizontal) as f32;
+            }
+            if s.has_axis(Axis::Vertical) {
+                pointer.scroll.1 -= s.scroll_value(Axis::Vertical) as f32;
+            }
+        }
+        _ => (),
+    }
+}
+
+/// Translates a Linux evdev keycode into a Varvara [`Key`].
+///
+/// Only the small subset of keys raven actually cares about are mapped;
+/// everything else is ignored rather than guessed at.
+fn decode_keycode(code: u32) -> Option<Key> {
+    // evdev keycodes, from <linux/input-event-codes.h>
+    const KEY_UP: u32 = 103;
+    const KEY_DOWN: u32 = 108;
+    const KEY_LEFT: u32 = 105;
+    const KEY_RIGHT: u32 = 106;
+    const KEY_LEFTCTRL: u32 = 29;
+    const KEY_LEFTALT: u32 = 56;
+    const KEY_LEFTSHIFT: u32 = 42;
+    const KEY_HOME: u32 = 102;
+
+    Some(match code {
+        KEY_UP => Key::Up,
+        KEY_DOWN => Key::Down,
+        KEY_LEFT => Key::Left,
+        KEY_RIGHT => Key::Right,
+        KEY_LEFTCTRL => Key::Ctrl,
+        KEY_LEFTALT => Key::Alt,
+        KEY_LEFTSHIFT => Key::Shift,
+        KEY_HOME => Key::Home,
+        _ => return None,
+    })
+}
+
+/// Releases DRM master on VT switch-away (`SIGUSR1`), re-acquiring it on
+/// switch-back (`SIGUSR2`); both signals are handled in `run`'s main loop
+/// via the `Signals` instance registered there.
+fn release_master(card: &impl ControlDevice) -> anyhow::Result<()> {
+    card.release_master_lock()?;
+    Ok(())
+}
+
+fn acquire_master(card: &impl ControlDevice) -> anyhow::Result<()> {
+    card.acquire_master_lock()?;
+    Ok(())
+}
@@ -0,0 +1,304 @@
+//! Deterministic input recording and replay, so a session (a TAS run, a
+//! bug report) can be reproduced pixel-for-pixel given the same ROM.
+//!
+//! A [`Journal`] is keyed by the same 60 FPS frame counter `Stage` already
+//! paces redraws with: every device-affecting event (key edges, mouse
+//! state, console bytes, char bytes) generated during a frame is recorded
+//! against that frame's index, and replay just pops them back out in
+//! order instead of reading live `egui` input.
+use std::io::{self, Read, Write};
+
+use varvara::{Key, MouseState};
+
+const MAGIC: &[u8; 4] = b"RVNJ";
+
+/// A single device-affecting event, timestamped implicitly by the frame
+/// it's stored under.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+    Pressed(Key),
+    Released(Key),
+    Mouse(MouseState),
+    Console(u8),
+    Char(u8),
+}
+
+/// All events recorded for one frame, plus which frame they belong to.
+struct Entry {
+    frame: u64,
+    events: Vec<Event>,
+}
+
+/// A loaded or in-progress recording.
+///
+/// Only frames with at least one event are stored; empty frames are
+/// implicit.
+pub struct Journal {
+    rom_hash: u64,
+    screen_size: (u16, u16),
+    entries: Vec<Entry>,
+}
+
+impl Journal {
+    pub fn new(rom_hash: u64, screen_size: (u16, u16)) -> Journal {
+        Journal {
+            rom_hash,
+            screen_size,
+            entries: Vec::new(),
+        }
+    }
+
+    /// Records `events` against `frame`, if non-empty. Frames must be
+    /// pushed in increasing order, matching how `Stage` paces itself.
+    pub fn push(&mut self, frame: u64, events: Vec<Event>) {
+        if !events.is_empty() {
+            self.entries.push(Entry { frame, events });
+        }
+    }
+
+    /// Checks that this journal was recorded against the ROM / screen
+    /// size currently in use, so a replay against a mismatched ROM is
+    /// rejected up front instead of silently desyncing.
+    pub fn check(&self, rom_hash: u64, screen_size: (u16, u16)) -> anyhow::Result<()> {
+        if self.rom_hash != rom_hash {
+            anyhow::bail!("journal was recorded against a different ROM");
+        }
+        if self.screen_size != screen_size {
+            anyhow::bail!(
+                "journal was recorded at screen size {:?}, got {:?}",
+                self.screen_size,
+                screen_size
+            );
+        }
+        Ok(())
+    }
+
+    pub fn write(&self, w: &mut impl Write) -> io::Result<()> {
+        w.write_all(MAGIC)?;
+        w.write_all(&self.rom_hash.to_le_bytes())?;
+        w.write_all(&self.screen_size.0.to_le_bytes())?;
+        w.write_all(&self.screen_size.1.to_le_bytes())?;
+
+        let mut prev_frame = 0u64;
+        for entry in &self.entries {
+            write_varint(w, entry.frame - prev_frame)?;
+            prev_frame = entry.frame;
+            write_varint(w, entry.events.len() as u64)?;
+            for e in &entry.events {
+                write_event(w, e)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn read(r: &mut impl Read) -> io::Result<Journal> {
+        let mut magic = [0u8; 4];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a raven input journal",
+            ));
+        }
+        let rom_hash = read_u64(r)?;
+        let w = read_u16(r)?;
+        let h = read_u16(r)?;
+
+        let mut entries = Vec::new();
+        let mut frame = 0u64;
+        loop {
+            let delta = match read_varint(r) {
+                Ok(d) => d,
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e),
+            };
+            frame += delta;
+            let count = read_varint(r)?;
+            let events =
+                (0..count).map(|_| read_event(r)).collect::<io::Result<_>>()?;
+            entries.push(Entry { frame, events });
+        }
+
+        Ok(Journal {
+            rom_hash,
+            screen_size: (w, h),
+            entries,
+        })
+    }
+}
+
+/// Walks a loaded [`Journal`] frame-by-frame during replay.
+pub struct Player {
+    journal: Journal,
+    next: usize,
+}
+
+impl Player {
+    pub fn new(journal: Journal) -> Player {
+        Player { journal, next: 0 }
+    }
+
+    /// Returns the events recorded for `frame`, if any were. Must be
+    /// called with non-decreasing `frame` values, one call per frame.
+    pub fn events_for(&mut self, frame: u64) -> &[Event] {
+        match self.journal.entries.get(self.next) {
+            Some(entry) if entry.frame == frame => {
+                self.next += 1;
+                &self.journal.entries[self.next - 1].events
+            }
+            _ => &[],
+        }
+    }
+}
+
+fn key_tag(k: Key) -> (u8, u8) {
+    match k {
+        Key::Up => (0, 0),
+        Key::Down => (1, 0),
+        Key::Left => (2, 0),
+        Key::Right => (3, 0),
+        Key::Home => (4, 0),
+        Key::Ctrl => (5, 0),
+        Key::Alt => (6, 0),
+        Key::Shift => (7, 0),
+        Key::Char(c) => (8, c),
+    }
+}
+
+fn key_from_tag(tag: u8, payload: u8) -> io::Result<Key> {
+    Ok(match tag {
+        0 => Key::Up,
+        1 => Key::Down,
+        2 => Key::Left,
+        3 => Key::Right,
+        4 => Key::Home,
+        5 => Key::Ctrl,
+        6 => Key::Alt,
+        7 => Key::Shift,
+        8 => Key::Char(payload),
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "invalid key tag in journal",
+            ))
+        }
+    })
+}
+
+fn write_event(w: &mut impl Write, e: &Event) -> io::Result<()> {
+    match e {
+        Event::Pressed(k) => {
+            let (tag, payload) = key_tag(*k);
+            w.write_all(&[0, tag, payload])
+        }
+        Event::Released(k) => {
+            let (tag, payload) = key_tag(*k);
+            w.write_all(&[1, tag, payload])
+        }
+        Event::Mouse(m) => {
+            w.write_all(&[2])?;
+            w.write_all(&m.pos.0.to_le_bytes())?;
+            w.write_all(&m.pos.1.to_le_bytes())?;
+            w.write_all(&m.scroll.0.to_le_bytes())?;
+            w.write_all(&m.scroll.1.to_le_bytes())?;
+            w.write_all(&[m.buttons])
+        }
+        Event::Console(c) => w.write_all(&[3, *c]),
+        Event::Char(c) => w.write_all(&[4, *c]),
+    }
+}
+
+fn read_event(r: &mut impl Read) -> io::Result<Event> {
+    let mut tag = [0u8; 1];
+    r.read_exact(&mut tag)?;
+    Ok(match tag[0] {
+        0 => {
+            let mut buf = [0u8; 2];
+            r.read_exact(&mut buf)?;
+            Event::Pressed(key_from_tag(buf[0], buf[1])?)
+        }
+        1 => {
+            let mut buf = [0u8; 2];
+            r.read_exact(&mut buf)?;
+            Event::Released(key_from_tag(buf[0], buf[1])?)
+        }
+        2 => {
+            let mut f = [0u8; 4];
+            let mut read_f32 = |r: &mut dyn Read| -> io::Result<f32> {
+                r.read_exact(&mut f)?;
+                Ok(f32::from_le_bytes(f))
+            };
+            let pos = (read_f32(r)?, read_f32(r)?);
+            let scroll = (read_f32(r)?, read_f32(r)?);
+            let mut b = [0u8; 1];
+            r.read_exact(&mut b)?;
+            Event::Mouse(MouseState {
+                pos,
+                scroll,
+                buttons: b[0],
+            })
+        }
+        3 => {
+            let mut b = [0u8; 1];
+            r.read_exact(&mut b)?;
+            Event::Console(b[0])
+        }
+        4 => {
+            let mut b = [0u8; 1];
+            r.read_exact(&mut b)?;
+            Event::Char(b[0])
+        }
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "invalid event tag in journal",
+            ))
+        }
+    })
+}
+
+fn write_varint(w: &mut impl Write, mut v: u64) -> io::Result<()> {
+    loop {
+        let byte = (v & 0x7f) as u8;
+        v >>= 7;
+        if v == 0 {
+            return w.write_all(&[byte]);
+        }
+        w.write_all(&[byte | 0x80])?;
+    }
+}
+
+fn read_varint(r: &mut impl Read) -> io::Result<u64> {
+    let mut result = 0u64;
+    let mut shift = 0;
+    loop {
+        let mut byte = [0u8; 1];
+        r.read_exact(&mut byte)?;
+        result |= u64::from(byte[0] & 0x7f) << shift;
+        if byte[0] & 0x80 == 0 {
+            return Ok(result);
+        }
+        shift += 7;
+    }
+}
+
+fn read_u64(r: &mut impl Read) -> io::Result<u64> {
+    let mut buf = [0u8; 8];
+    r.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn read_u16(r: &mut impl Read) -> io::Result<u16> {
+    let mut buf = [0u8; 2];
+    r.read_exact(&mut buf)?;
+    Ok(u16::from_le_bytes(buf))
+}
+
+/// Hashes ROM bytes for the journal header, so a replay against a
+/// different ROM is rejected rather than silently desyncing.
+pub fn hash_rom(rom: &[u8]) -> u64 {
+    use std::hash::{Hash, Hasher};
+    let mut h = std::collections::hash_map::DefaultHasher::new();
+    rom.hash(&mut h);
+    h.finish()
+}
@@ -0,0 +1,175 @@
+//! Keyboard layout support.
+//!
+//! `decode_key` used to be a hardcoded US-QWERTY `match`, which meant
+//! anyone on a different physical layout got wrong characters out of
+//! Shift combinations egui already decodes correctly via `Key`. A
+//! [`Keymap`] is just that match turned into a lookup table selectable by
+//! name, with a `"us"` table reproducing the old hardcoded behavior.
+use eframe::egui;
+use log::warn;
+use varvara::Key;
+
+/// The subset of egui's `Modifiers` that can change what a key produces.
+/// Tracked as our own small struct (rather than `egui::Modifiers`
+/// directly) so it can be used as a hash key.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub struct Mods {
+    pub shift: bool,
+    pub ctrl: bool,
+    pub alt: bool,
+}
+
+impl From<egui::Modifiers> for Mods {
+    fn from(m: egui::Modifiers) -> Self {
+        Mods {
+            shift: m.shift,
+            ctrl: m.ctrl,
+            alt: m.alt,
+        }
+    }
+}
+
+/// A table mapping `(egui::Key, modifiers)` to the [`Key`] raven feeds to
+/// the emulated device, selectable by name so alternate layouts can be
+/// added without touching `Stage`.
+pub struct Keymap {
+    name: &'static str,
+    table: std::collections::HashMap<(egui::Key, Mods), Key>,
+}
+
+impl Keymap {
+    /// Looks up a built-in layout by name. `"us"` is the only layout built
+    /// in today; anything else falls back to it with a warning, so a
+    /// mistyped `--layout` flag degrades gracefully instead of
+    /// refusing to start.
+    pub fn named(name: &str) -> Keymap {
+        if name != "us" {
+            warn!("unknown keyboard layout {name:?}, falling back to \"us\"");
+        }
+        Keymap::us()
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Translates a key press/release under the given modifiers. Returns
+    /// `None` if this layout doesn't map the combination to anything; the
+    /// caller should fall back to `egui::Event::Text` bytes in that case
+    /// (which also covers dead keys and IME composition, neither of
+    /// which ever reach us as a `Key` event).
+    pub fn decode(&self, key: egui::Key, modifiers: egui::Modifiers) -> Option<Key> {
+        let mods = Mods::from(modifiers);
+        // The table only ever stores entries with `ctrl`/`alt` both false
+        // (nothing in `us()` varies on them), so falling back all the way
+        // to `Mods::default()` would drop `shift` too and turn e.g.
+        // Ctrl+Shift+A into lowercase `a`. Keep `shift`, drop only the
+        // modifiers the table never distinguishes on.
+        let fallback = Mods { shift: mods.shift, ..Mods::default() };
+        self.table
+            .get(&(key, mods))
+            .or_else(|| self.table.get(&(key, fallback)))
+            .copied()
+    }
+
+    /// The default US-QWERTY layout, reproducing the behavior of the
+    /// original hardcoded `decode_key` match, except that Shift+`Num5` now
+    /// gives `%` instead of `5`. Quote still isn't delivered as a `Key`
+    /// event by egui and so falls through to the `Text` fallback as before.
+    fn us() -> Keymap {
+        let mut table = std::collections::HashMap::new();
+        // Keys that produce the same thing with or without Shift.
+        let mut any = |k: egui::Key, c: u8| {
+            for shift in [false, true] {
+                table.insert((k, Mods { shift, ..Mods::default() }), Key::Char(c));
+            }
+        };
+        for (k, c) in [
+            (egui::Key::Backslash, b'\\' as char),
+            (egui::Key::Pipe, b'|' as char),
+            (egui::Key::Equals, b'=' as char),
+            (egui::Key::Plus, b'+' as char),
+            (egui::Key::Semicolon, b';' as char),
+            (egui::Key::Colon, b':' as char),
+            (egui::Key::Slash, b'/' as char),
+            (egui::Key::Questionmark, b'?' as char),
+            (egui::Key::Space, b' ' as char),
+            (egui::Key::Tab, b'\t' as char),
+            (egui::Key::Enter, b'\r' as char),
+        ] {
+            any(k, c as u8);
+        }
+
+        let mut shift = |k: egui::Key, shift: bool, c: u8| {
+            table.insert((k, Mods { shift, ..Mods::default() }), Key::Char(c));
+        };
+        for (k, lo, hi) in [
+            (egui::Key::Num0, b'0', b')'),
+            (egui::Key::Num1, b'1', b'!'),
+            (egui::Key::Num2, b'2', b'@'),
+            (egui::Key::Num3, b'3', b'#'),
+            (egui::Key::Num4, b'4', b'$'),
+            (egui::Key::Num5, b'5', b'%'),
+            (egui::Key::Num6, b'6', b'^'),
+            (egui::Key::Num7, b'7', b'&'),
+            (egui::Key::Num8, b'8', b'*'),
+            (egui::Key::Num9, b'9', b'('),
+            (egui::Key::Backtick, b'`', b'~'),
+            (egui::Key::Comma, b',', b'<'),
+            (egui::Key::OpenBracket, b'[', b'{'),
+            (egui::Key::Minus, b'-', b'_'),
+            (egui::Key::Period, b'.', b'>'),
+            (egui::Key::CloseBracket, b']', b'}'),
+        ] {
+            shift(k, false, lo);
+            shift(k, true, hi);
+        }
+
+        for (k, lo, hi) in [
+            (egui::Key::A, b'a', b'A'),
+            (egui::Key::B, b'b', b'B'),
+            (egui::Key::C, b'c', b'C'),
+            (egui::Key::D, b'd', b'D'),
+            (egui::Key::E, b'e', b'E'),
+            (egui::Key::F, b'f', b'F'),
+            (egui::Key::G, b'g', b'G'),
+            (egui::Key::H, b'h', b'H'),
+            (egui::Key::I, b'i', b'I'),
+            (egui::Key::J, b'j', b'J'),
+            (egui::Key::K, b'k', b'K'),
+            (egui::Key::L, b'l', b'L'),
+            (egui::Key::M, b'm', b'M'),
+            (egui::Key::N, b'n', b'N'),
+            (egui::Key::O, b'o', b'O'),
+            (egui::Key::P, b'p', b'P'),
+            (egui::Key::Q, b'q', b'Q'),
+            (egui::Key::R, b'r', b'R'),
+            (egui::Key::S, b's', b'S'),
+            (egui::Key::T, b't', b'T'),
+            (egui::Key::U, b'u', b'U'),
+            (egui::Key::V, b'v', b'V'),
+            (egui::Key::W, b'w', b'W'),
+            (egui::Key::X, b'x', b'X'),
+            (egui::Key::Y, b'y', b'Y'),
+            (egui::Key::Z, b'z', b'Z'),
+        ] {
+            shift(k, false, lo);
+            shift(k, true, hi);
+        }
+
+        let mut table = table;
+        for (k, key) in [
+            (egui::Key::ArrowUp, Key::Up),
+            (egui::Key::ArrowDown, Key::Down),
+            (egui::Key::ArrowLeft, Key::Left),
+            (egui::Key::ArrowRight, Key::Right),
+            (egui::Key::Home, Key::Home),
+        ] {
+            for shift in [false, true] {
+                table.insert((k, Mods { shift, ..Mods::default() }), key);
+            }
+        }
+
+        Keymap { name: "us", table }
+    }
+}
@@ -3,12 +3,81 @@ use log::{error, info};
 use uxn::Uxn;
 use varvara::{Key, MouseState, Varvara, AUDIO_CHANNELS, AUDIO_SAMPLE_RATE};
 
+use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 
 use cpal::traits::StreamTrait;
 use eframe::egui;
 use log::warn;
 
+/// Dead-zone threshold for converting a stick axis into D-pad presses.
+///
+/// We apply hysteresis around this value (see [`GamepadAxisState`]) so a
+/// stick resting near the boundary doesn't spam press/release events.
+const AXIS_DEAD_ZONE: f32 = 0.5;
+const AXIS_HYSTERESIS: f32 = 0.1;
+
+/// Tracks whether each axis of a single stick is currently "pressed" as a
+/// D-pad direction, so we only emit edges (not repeated presses).
+#[derive(Copy, Clone, Default)]
+struct GamepadAxisState {
+    neg: bool,
+    pos: bool,
+}
+
+impl GamepadAxisState {
+    /// Updates state for one axis value, returning `(lo, hi)` edges that
+    /// changed this call, e.g. `(Some(true), None)` means the negative
+    /// direction just became pressed.
+    fn update(&mut self, value: f32) -> (Option<bool>, Option<bool>) {
+        let neg = if self.neg {
+            value < -(AXIS_DEAD_ZONE - AXIS_HYSTERESIS)
+        } else {
+            value < -AXIS_DEAD_ZONE
+        };
+        let pos = if self.pos {
+            value > AXIS_DEAD_ZONE - AXIS_HYSTERESIS
+        } else {
+            value > AXIS_DEAD_ZONE
+        };
+        let neg_edge = (neg != self.neg).then_some(neg);
+        let pos_edge = (pos != self.pos).then_some(pos);
+        self.neg = neg;
+        self.pos = pos;
+        (neg_edge, pos_edge)
+    }
+}
+
+/// Per-pad state used to turn the left stick's raw axis values into
+/// synthetic D-pad press/release edges, and to track which of the
+/// keyboard-modifier buttons this particular pad is holding.
+///
+/// The modifier fields can't be pressed/released directly from
+/// `poll_gamepads` like other buttons: the keyboard handling in `update`
+/// owns `Key::Ctrl`/`Alt`/`Shift` and sends a release whenever its tracked
+/// modifier state drops, which would release a modifier the gamepad is
+/// still holding. Instead each pad ORs its state in here, and the
+/// keyboard sync reads the OR of every pad via `Stage::gamepad_mods` —
+/// tracked per pad (rather than in one shared struct) so one pad
+/// releasing a button can't clobber another pad still holding it.
+#[derive(Copy, Clone, Default)]
+struct GamepadState {
+    x: GamepadAxisState,
+    y: GamepadAxisState,
+    ctrl: bool,
+    alt: bool,
+    shift: bool,
+}
+
+/// The OR of every connected pad's modifier-button state; see
+/// `GamepadState`.
+#[derive(Copy, Clone, Default)]
+struct GamepadMods {
+    ctrl: bool,
+    alt: bool,
+    shift: bool,
+}
+
 pub struct Stage<'a> {
     vm: Uxn<'a>,
     dev: Varvara,
@@ -19,17 +88,64 @@ pub struct Stage<'a> {
     #[cfg(not(target_arch = "wasm32"))]
     console_rx: std::sync::mpsc::Receiver<u8>,
 
+    #[cfg(not(target_arch = "wasm32"))]
+    gilrs: gilrs::Gilrs,
+    #[cfg(not(target_arch = "wasm32"))]
+    gamepads: HashMap<gilrs::GamepadId, GamepadState>,
+
+    keymap: keymap::Keymap,
+
+    /// 60 FPS frame counter, incremented every time `next_frame` gates a
+    /// redraw; doubles as the index input journals are keyed by.
+    frame: u64,
+    journal: Option<JournalMode>,
+
     scroll: (f32, f32),
     cursor_pos: Option<(f32, f32)>,
 
+    /// Ctrl/Alt/Shift state last sent to `self.dev`, so the per-frame
+    /// keyboard/gamepad modifier resync only calls `dev_pressed`/
+    /// `dev_released` on an actual edge instead of every frame.
+    mods_held: GamepadMods,
+
     texture: egui::TextureHandle,
 }
 
+/// Either recording live input to a [`journal::Journal`] or replaying one
+/// in place of it.
+enum JournalMode {
+    Record {
+        journal: journal::Journal,
+        pending: Vec<journal::Event>,
+        path: std::path::PathBuf,
+    },
+    Replay {
+        player: journal::Player,
+    },
+}
+
+impl Drop for JournalMode {
+    /// Flushes a recording to disk once the session ends, so `--record`
+    /// doesn't require a clean exit path to actually produce a file.
+    fn drop(&mut self) {
+        if let JournalMode::Record { journal, path, .. } = self {
+            let result = std::fs::File::create(path.as_path())
+                .map(std::io::BufWriter::new)
+                .and_then(|mut f| journal.write(&mut f));
+            if let Err(e) = result {
+                error!("failed to save input journal to {path:?}: {e}");
+            }
+        }
+    }
+}
+
 impl<'a> Stage<'a> {
     pub fn new(
         vm: Uxn<'a>,
         mut dev: Varvara,
         ctx: &egui::Context,
+        keymap: keymap::Keymap,
+        journal: Option<JournalMode>,
     ) -> Stage<'a> {
         let out = dev.output(&vm);
 
@@ -51,54 +167,280 @@ impl<'a> Stage<'a> {
             #[cfg(not(target_arch = "wasm32"))]
             console_rx: varvara::console_worker(),
 
+            #[cfg(not(target_arch = "wasm32"))]
+            gilrs: gilrs::Gilrs::new().expect("could not initialize gilrs"),
+            #[cfg(not(target_arch = "wasm32"))]
+            gamepads: HashMap::new(),
+
+            keymap,
+
+            frame: 0,
+            journal,
+
             scroll: (0.0, 0.0),
             cursor_pos: None,
 
+            mods_held: GamepadMods::default(),
+
             texture,
         }
     }
+
+    /// Opens `path` as a fresh recording for a ROM whose bytes hash to
+    /// `rom_hash`, sized to `screen_size`; used by [`run_backend`] for
+    /// `RAVEN_RECORD`.
+    pub fn start_recording(
+        path: std::path::PathBuf,
+        rom_hash: u64,
+        screen_size: (u16, u16),
+    ) -> JournalMode {
+        JournalMode::Record {
+            journal: journal::Journal::new(rom_hash, screen_size),
+            pending: Vec::new(),
+            path,
+        }
+    }
+
+    /// Loads `path` as a journal to replay, checking it was recorded
+    /// against the same ROM and screen size; used by [`run_backend`] for
+    /// `RAVEN_REPLAY`.
+    pub fn start_replay(
+        path: &std::path::Path,
+        rom_hash: u64,
+        screen_size: (u16, u16),
+    ) -> anyhow::Result<JournalMode> {
+        let mut f = std::io::BufReader::new(std::fs::File::open(path)?);
+        let journal = journal::Journal::read(&mut f)?;
+        journal.check(rom_hash, screen_size)?;
+        Ok(JournalMode::Replay {
+            player: journal::Player::new(journal),
+        })
+    }
+
+    fn is_replaying(&self) -> bool {
+        matches!(self.journal, Some(JournalMode::Replay { .. }))
+    }
+
+    fn record(&mut self, e: journal::Event) {
+        if let Some(JournalMode::Record { pending, .. }) = &mut self.journal {
+            pending.push(e);
+        }
+    }
+
+    fn dev_pressed(&mut self, k: Key) {
+        self.record(journal::Event::Pressed(k));
+        self.dev.pressed(&mut self.vm, k);
+    }
+
+    fn dev_released(&mut self, k: Key) {
+        self.record(journal::Event::Released(k));
+        self.dev.released(&mut self.vm, k);
+    }
+
+    fn dev_mouse(&mut self, m: MouseState) {
+        self.record(journal::Event::Mouse(m));
+        self.dev.mouse(&mut self.vm, m);
+    }
+
+    fn dev_console(&mut self, c: u8) {
+        self.record(journal::Event::Console(c));
+        self.dev.console(&mut self.vm, c);
+    }
+
+    fn dev_char(&mut self, c: u8) {
+        self.record(journal::Event::Char(c));
+        self.dev.char(&mut self.vm, c);
+    }
+
+    /// Advances the frame counter, flushing whatever was buffered this
+    /// frame into the journal if we're recording.
+    fn advance_frame(&mut self) {
+        if let Some(JournalMode::Record {
+            journal, pending, ..
+        }) = &mut self.journal
+        {
+            journal.push(self.frame, std::mem::take(pending));
+        }
+        self.frame += 1;
+    }
+
+    /// Feeds this frame's recorded events (if any) to `self.dev`, used
+    /// instead of live input while replaying.
+    fn replay_frame(&mut self) {
+        let Some(JournalMode::Replay { player }) = &mut self.journal else {
+            return;
+        };
+        for e in player.events_for(self.frame).to_vec() {
+            match e {
+                journal::Event::Pressed(k) => self.dev.pressed(&mut self.vm, k),
+                journal::Event::Released(k) => {
+                    self.dev.released(&mut self.vm, k)
+                }
+                journal::Event::Mouse(m) => self.dev.mouse(&mut self.vm, m),
+                journal::Event::Console(c) => self.dev.console(&mut self.vm, c),
+                journal::Event::Char(c) => self.dev.char(&mut self.vm, c),
+            }
+        }
+    }
+
+    /// Drains pending gamepad events and forwards them to `self.dev`.
+    ///
+    /// Button presses are mapped onto the Varvara controller's existing
+    /// auxiliary buttons (`Ctrl`/`Alt`/`Shift`/`Home`), since that's the
+    /// full set the device exposes beyond the D-pad; the left stick is
+    /// converted into synthetic D-pad presses with hysteresis so jitter
+    /// near the dead-zone boundary doesn't spam edges.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn poll_gamepads(&mut self) {
+        use gilrs::{Axis, Button, EventType};
+
+        // While replaying, gamepad state is whatever's in the journal;
+        // draining live events here would double up on input.
+        if self.is_replaying() {
+            return;
+        }
+
+        while let Some(gilrs::Event { id, event, .. }) = self.gilrs.next_event()
+        {
+            match event {
+                EventType::ButtonPressed(button, _)
+                | EventType::ButtonReleased(button, _) => {
+                    let pressed =
+                        matches!(event, EventType::ButtonPressed(..));
+                    let k = match button {
+                        Button::DPadUp => Some(Key::Up),
+                        Button::DPadDown => Some(Key::Down),
+                        Button::DPadLeft => Some(Key::Left),
+                        Button::DPadRight => Some(Key::Right),
+                        Button::Start => Some(Key::Home),
+                        // South/East/Select map onto the same
+                        // Ctrl/Alt/Shift keys the keyboard modifiers
+                        // drive; see `GamepadState` for why they're
+                        // tracked per pad instead of pressed here.
+                        Button::South => {
+                            self.gamepads.entry(id).or_default().ctrl = pressed;
+                            None
+                        }
+                        Button::East => {
+                            self.gamepads.entry(id).or_default().alt = pressed;
+                            None
+                        }
+                        Button::Select => {
+                            self.gamepads.entry(id).or_default().shift = pressed;
+                            None
+                        }
+                        // West / North have no corresponding button on the
+                        // Varvara controller device, so they're dropped.
+                        Button::West | Button::North => None,
+                        _ => None,
+                    };
+                    if let Some(k) = k {
+                        if pressed {
+                            self.dev_pressed(k);
+                        } else {
+                            self.dev_released(k);
+                        }
+                    }
+                }
+                EventType::AxisChanged(axis, value, _) => {
+                    let state = self.gamepads.entry(id).or_default();
+                    let (axis_state, neg_key, pos_key) = match axis {
+                        Axis::LeftStickX => {
+                            (&mut state.x, Key::Left, Key::Right)
+                        }
+                        Axis::LeftStickY => {
+                            // Stick up is a positive Y value.
+                            (&mut state.y, Key::Down, Key::Up)
+                        }
+                        _ => continue,
+                    };
+                    let (neg_edge, pos_edge) = axis_state.update(value);
+                    for (edge, key) in
+                        [(neg_edge, neg_key), (pos_edge, pos_key)]
+                    {
+                        match edge {
+                            Some(true) => self.dev_pressed(key),
+                            Some(false) => self.dev_released(key),
+                            None => (),
+                        }
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+
+    /// The OR of every connected pad's modifier-button state, so one pad
+    /// releasing South/East/Select doesn't drop a modifier another pad is
+    /// still holding.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn gamepad_mods(&self) -> GamepadMods {
+        self.gamepads.values().fold(GamepadMods::default(), |acc, s| {
+            GamepadMods {
+                ctrl: acc.ctrl || s.ctrl,
+                alt: acc.alt || s.alt,
+                shift: acc.shift || s.shift,
+            }
+        })
+    }
 }
 
 impl eframe::App for Stage<'_> {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         // Repaint at vsync rate (60 FPS)
         ctx.request_repaint();
+
+        let replaying = self.is_replaying();
+
+        #[cfg(not(target_arch = "wasm32"))]
+        self.poll_gamepads();
+
+        let mut advanced = false;
         ctx.input(|i| {
             if i.time >= self.next_frame {
                 // Screen callback (limited to 60 FPS).  We want to err on the
                 // side of redrawing early, rather than missing frames.
                 self.next_frame = i.time + 0.015;
                 self.dev.redraw(&mut self.vm);
+                advanced = true;
             }
 
-            let shift_held = i.modifiers.shift;
+            // While replaying, live input is ignored entirely; the
+            // journal is the only source of truth for this frame.
+            if replaying {
+                return;
+            }
+
+            // The Text event doesn't handle Ctrl + characters, so normal
+            // typing goes through the Key event (via `self.keymap`). Any
+            // glyph the keymap doesn't cover for this frame (quotes, other
+            // layout-specific symbols, dead keys, IME composition) instead
+            // reaches us only as `Text`, so we track which bytes were
+            // already delivered via a Key event and forward the rest.
+            let mut chars_via_keymap = std::collections::HashSet::new();
             for e in i.events.iter() {
                 match e {
-                    egui::Event::Text(s) => {
-                        // The Text event doesn't handle Ctrl + characters, so
-                        // we do everything through the Key event, with the
-                        // exception of quotes (which don't have an associated
-                        // key; https://github.com/emilk/egui/pull/4683)
-                        //
-                        // Similarly, the Key event doesn't always decode
-                        // events with Shift and an attached key.  This is all
-                        // terribly messy; my apologies.
-                        const RAW_CHARS: [u8; 16] = [
-                            b'"', b'\'', b'{', b'}', b'_', b')', b'(', b'*',
-                            b'&', b'^', b'%', b'$', b'#', b'@', b'!', b'~',
-                        ];
-                        for c in s.bytes() {
-                            if RAW_CHARS.contains(&c) {
-                                self.dev.char(&mut self.vm, c);
+                    egui::Event::Key {
+                        key,
+                        pressed,
+                        modifiers,
+                        ..
+                    } => {
+                        if let Some(k) = self.keymap.decode(*key, *modifiers) {
+                            if let Key::Char(c) = k {
+                                chars_via_keymap.insert(c);
                             }
-                        }
-                    }
-                    egui::Event::Key { key, pressed, .. } => {
-                        if let Some(k) = decode_key(*key, shift_held) {
                             if *pressed {
-                                self.dev.pressed(&mut self.vm, k);
+                                self.dev_pressed(k);
                             } else {
-                                self.dev.released(&mut self.vm, k);
+                                self.dev_released(k);
+                            }
+                        }
+                    }
+                    egui::Event::Text(s) => {
+                        for c in s.bytes() {
+                            if !chars_via_keymap.contains(&c) {
+                                self.dev_char(c);
                             }
                         }
                     }
@@ -109,17 +451,36 @@ impl eframe::App for Stage<'_> {
                     _ => (),
                 }
             }
-            for (b, k) in [
-                (i.modifiers.ctrl, Key::Ctrl),
-                (i.modifiers.alt, Key::Alt),
-                (i.modifiers.shift, Key::Shift),
+            // OR in gamepad South/East/Select so this resync doesn't
+            // immediately release a modifier the gamepad (rather than the
+            // physical keyboard) is holding; see `GamepadState`.
+            #[cfg(not(target_arch = "wasm32"))]
+            let gamepad_mods = self.gamepad_mods();
+            #[cfg(target_arch = "wasm32")]
+            let gamepad_mods = GamepadMods::default();
+            let new_mods = GamepadMods {
+                ctrl: i.modifiers.ctrl || gamepad_mods.ctrl,
+                alt: i.modifiers.alt || gamepad_mods.alt,
+                shift: i.modifiers.shift || gamepad_mods.shift,
+            };
+            // Only press/release on an actual edge: this also feeds the
+            // input journal (via `dev_pressed`/`dev_released`), and
+            // re-sending all three every frame regardless of change would
+            // add three key events to every recorded frame.
+            for (was, is, k) in [
+                (self.mods_held.ctrl, new_mods.ctrl, Key::Ctrl),
+                (self.mods_held.alt, new_mods.alt, Key::Alt),
+                (self.mods_held.shift, new_mods.shift, Key::Shift),
             ] {
-                if b {
-                    self.dev.pressed(&mut self.vm, k)
-                } else {
-                    self.dev.released(&mut self.vm, k)
+                if was != is {
+                    if is {
+                        self.dev_pressed(k)
+                    } else {
+                        self.dev_released(k)
+                    }
                 }
             }
+            self.mods_held = new_mods;
 
             let ptr = &i.pointer;
             if let Some(p) = ptr.latest_pos() {
@@ -140,14 +501,22 @@ impl eframe::App for Stage<'_> {
                 scroll: std::mem::take(&mut self.scroll),
                 buttons,
             };
-            self.dev.mouse(&mut self.vm, m);
-            i.time
+            self.dev_mouse(m);
         });
 
         // Listen for console characters
         #[cfg(not(target_arch = "wasm32"))]
-        if let Ok(c) = self.console_rx.try_recv() {
-            self.dev.console(&mut self.vm, c);
+        if !replaying {
+            if let Ok(c) = self.console_rx.try_recv() {
+                self.dev_console(c);
+            }
+        }
+
+        if advanced {
+            if replaying {
+                self.replay_frame();
+            }
+            self.advance_frame();
         }
 
         // Handle audio callback
@@ -195,6 +564,72 @@ impl eframe::App for Stage<'_> {
     }
 }
 
+/// Converts between the fixed rate/channel count Varvara renders audio at
+/// and whatever the output device actually negotiated.
+///
+/// Samples are pulled one source frame at a time through `pull_source` and
+/// linearly interpolated onto a fractional read cursor that carries over
+/// between callbacks, so there's no audible seam at buffer boundaries.
+/// Channel-count mismatches are handled by duplicating (upmix) or
+/// discarding (downmix) source channels via modulo indexing, rather than
+/// refusing to open the device.
+struct Resampler {
+    src_channels: usize,
+    dst_channels: usize,
+    /// Source frames consumed per destination frame.
+    ratio: f64,
+    /// Fractional position of the next destination frame, in units of
+    /// buffered source frames (`ring[0]` is source frame 0).
+    cursor: f64,
+    ring: std::collections::VecDeque<Vec<f32>>,
+}
+
+impl Resampler {
+    fn new(
+        src_rate: u32,
+        dst_rate: u32,
+        src_channels: usize,
+        dst_channels: usize,
+    ) -> Self {
+        Resampler {
+            src_channels,
+            dst_channels,
+            ratio: f64::from(src_rate) / f64::from(dst_rate),
+            cursor: 0.0,
+            ring: std::collections::VecDeque::new(),
+        }
+    }
+
+    /// Fills `out` (interleaved, `dst_channels`-wide) with resampled
+    /// audio, calling `pull_source` to decode one more source frame
+    /// (interleaved, `src_channels`-wide) whenever the ring buffer runs
+    /// dry. If `pull_source` can't keep up, missing source frames default
+    /// to silence rather than underrunning the output.
+    fn fill(&mut self, out: &mut [f32], mut pull_source: impl FnMut(&mut [f32])) {
+        for frame in out.chunks_mut(self.dst_channels) {
+            while self.ring.len() <= self.cursor as usize + 1 {
+                let mut src_frame = vec![0.0f32; self.src_channels];
+                pull_source(&mut src_frame);
+                self.ring.push_back(src_frame);
+            }
+
+            let i0 = self.cursor as usize;
+            let frac = (self.cursor - i0 as f64) as f32;
+            for (ch, o) in frame.iter_mut().enumerate() {
+                let s0 = self.ring[i0][ch % self.src_channels];
+                let s1 = self.ring[i0 + 1][ch % self.src_channels];
+                *o = s0 + (s1 - s0) * frac;
+            }
+
+            self.cursor += self.ratio;
+            while self.cursor >= 1.0 && self.ring.len() > 1 {
+                self.ring.pop_front();
+                self.cursor -= 1.0;
+            }
+        }
+    }
+}
+
 pub fn audio_setup(
     data: [Arc<Mutex<varvara::StreamData>>; 4],
 ) -> (cpal::Device, [cpal::Stream; 4]) {
@@ -203,24 +638,59 @@ pub fn audio_setup(
     let device = host
         .default_output_device()
         .expect("no output device available");
-    let mut supported_configs_range = device
-        .supported_output_configs()
-        .expect("error while querying configs");
 
-    let supported_config = supported_configs_range
+    let default_rate = device
+        .default_output_config()
+        .map(|c| c.sample_rate().0)
+        .unwrap_or(AUDIO_SAMPLE_RATE);
+
+    let supported_configs: Vec<_> = device
+        .supported_output_configs()
+        .expect("error while querying configs")
+        .collect();
+    let supported_config = supported_configs
+        .iter()
         .find_map(|c| {
             c.try_with_sample_rate(cpal::SampleRate(AUDIO_SAMPLE_RATE))
         })
-        .filter(|c| usize::from(c.channels()) == AUDIO_CHANNELS)
+        .or_else(|| {
+            // No config natively supports our rate; fall back to the
+            // device's own default rate, clamped into whatever range the
+            // closest config supports.
+            let range = supported_configs.iter().min_by_key(|c| {
+                default_rate
+                    .clamp(c.min_sample_rate().0, c.max_sample_rate().0)
+                    .abs_diff(default_rate)
+            })?;
+            let rate = default_rate
+                .clamp(range.min_sample_rate().0, range.max_sample_rate().0);
+            Some(range.clone().with_sample_rate(cpal::SampleRate(rate)))
+        })
         .expect("no supported config?");
     let config = supported_config.config();
+    let device_rate = config.sample_rate.0;
+    let device_channels = usize::from(config.channels);
+
+    if device_rate != AUDIO_SAMPLE_RATE || device_channels != AUDIO_CHANNELS {
+        info!(
+            "resampling audio: varvara renders {AUDIO_SAMPLE_RATE} Hz / \
+             {AUDIO_CHANNELS}ch, device wants {device_rate} Hz / \
+             {device_channels}ch"
+        );
+    }
 
     let streams = data.map(|d| {
+        let mut resampler = Resampler::new(
+            AUDIO_SAMPLE_RATE,
+            device_rate,
+            AUDIO_CHANNELS,
+            device_channels,
+        );
         let stream = device
             .build_output_stream(
                 &config,
-                move |data: &mut [f32], _opt: &cpal::OutputCallbackInfo| {
-                    d.lock().unwrap().next(data);
+                move |out: &mut [f32], _opt: &cpal::OutputCallbackInfo| {
+                    resampler.fill(out, |src| d.lock().unwrap().next(src));
                 },
                 move |err| {
                     panic!("{err}");
@@ -234,120 +704,114 @@ pub fn audio_setup(
     (device, streams)
 }
 
-fn decode_key(k: egui::Key, shift: bool) -> Option<Key> {
-    let c = match (k, shift) {
-        (egui::Key::ArrowUp, _) => Key::Up,
-        (egui::Key::ArrowDown, _) => Key::Down,
-        (egui::Key::ArrowLeft, _) => Key::Left,
-        (egui::Key::ArrowRight, _) => Key::Right,
-        (egui::Key::Home, _) => Key::Home,
-        (egui::Key::Num0, false) => Key::Char(b'0'),
-        (egui::Key::Num0, true) => Key::Char(b')'),
-        (egui::Key::Num1, false) => Key::Char(b'1'),
-        (egui::Key::Num1, true) => Key::Char(b'!'),
-        (egui::Key::Num2, false) => Key::Char(b'2'),
-        (egui::Key::Num2, true) => Key::Char(b'@'),
-        (egui::Key::Num3, false) => Key::Char(b'3'),
-        (egui::Key::Num3, true) => Key::Char(b'#'),
-        (egui::Key::Num4, false) => Key::Char(b'4'),
-        (egui::Key::Num4, true) => Key::Char(b'$'),
-        (egui::Key::Num5, false) => Key::Char(b'5'),
-        (egui::Key::Num5, true) => Key::Char(b'5'),
-        (egui::Key::Num6, false) => Key::Char(b'6'),
-        (egui::Key::Num6, true) => Key::Char(b'^'),
-        (egui::Key::Num7, false) => Key::Char(b'7'),
-        (egui::Key::Num7, true) => Key::Char(b'&'),
-        (egui::Key::Num8, false) => Key::Char(b'8'),
-        (egui::Key::Num8, true) => Key::Char(b'*'),
-        (egui::Key::Num9, false) => Key::Char(b'9'),
-        (egui::Key::Num9, true) => Key::Char(b'('),
-        (egui::Key::A, false) => Key::Char(b'a'),
-        (egui::Key::A, true) => Key::Char(b'A'),
-        (egui::Key::B, false) => Key::Char(b'b'),
-        (egui::Key::B, true) => Key::Char(b'B'),
-        (egui::Key::C, false) => Key::Char(b'c'),
-        (egui::Key::C, true) => Key::Char(b'C'),
-        (egui::Key::D, false) => Key::Char(b'd'),
-        (egui::Key::D, true) => Key::Char(b'D'),
-        (egui::Key::E, false) => Key::Char(b'e'),
-        (egui::Key::E, true) => Key::Char(b'E'),
-        (egui::Key::F, false) => Key::Char(b'f'),
-        (egui::Key::F, true) => Key::Char(b'F'),
-        (egui::Key::G, false) => Key::Char(b'g'),
-        (egui::Key::G, true) => Key::Char(b'G'),
-        (egui::Key::H, false) => Key::Char(b'h'),
-        (egui::Key::H, true) => Key::Char(b'H'),
-        (egui::Key::I, false) => Key::Char(b'i'),
-        (egui::Key::I, true) => Key::Char(b'I'),
-        (egui::Key::J, false) => Key::Char(b'j'),
-        (egui::Key::J, true) => Key::Char(b'J'),
-        (egui::Key::K, false) => Key::Char(b'k'),
-        (egui::Key::K, true) => Key::Char(b'K'),
-        (egui::Key::L, false) => Key::Char(b'l'),
-        (egui::Key::L, true) => Key::Char(b'L'),
-        (egui::Key::M, false) => Key::Char(b'm'),
-        (egui::Key::M, true) => Key::Char(b'M'),
-        (egui::Key::N, false) => Key::Char(b'n'),
-        (egui::Key::N, true) => Key::Char(b'N'),
-        (egui::Key::O, false) => Key::Char(b'o'),
-        (egui::Key::O, true) => Key::Char(b'O'),
-        (egui::Key::P, false) => Key::Char(b'p'),
-        (egui::Key::P, true) => Key::Char(b'P'),
-        (egui::Key::Q, false) => Key::Char(b'q'),
-        (egui::Key::Q, true) => Key::Char(b'Q'),
-        (egui::Key::R, false) => Key::Char(b'r'),
-        (egui::Key::R, true) => Key::Char(b'R'),
-        (egui::Key::S, false) => Key::Char(b's'),
-        (egui::Key::S, true) => Key::Char(b'S'),
-        (egui::Key::T, false) => Key::Char(b't'),
-        (egui::Key::T, true) => Key::Char(b'T'),
-        (egui::Key::U, false) => Key::Char(b'u'),
-        (egui::Key::U, true) => Key::Char(b'U'),
-        (egui::Key::V, false) => Key::Char(b'v'),
-        (egui::Key::V, true) => Key::Char(b'V'),
-        (egui::Key::W, false) => Key::Char(b'w'),
-        (egui::Key::W, true) => Key::Char(b'W'),
-        (egui::Key::X, false) => Key::Char(b'x'),
-        (egui::Key::X, true) => Key::Char(b'X'),
-        (egui::Key::Y, false) => Key::Char(b'y'),
-        (egui::Key::Y, true) => Key::Char(b'Y'),
-        (egui::Key::Z, false) => Key::Char(b'z'),
-        (egui::Key::Z, true) => Key::Char(b'Z'),
-        // TODO missing Key::Quote
-        (egui::Key::Backtick, false) => Key::Char(b'`'),
-        (egui::Key::Backtick, true) => Key::Char(b'~'),
-        (egui::Key::Backslash, _) => Key::Char(b'\\'),
-        (egui::Key::Pipe, _) => Key::Char(b'|'),
-        (egui::Key::Comma, false) => Key::Char(b','),
-        (egui::Key::Comma, true) => Key::Char(b'<'),
-        (egui::Key::Equals, _) => Key::Char(b'='),
-        (egui::Key::Plus, _) => Key::Char(b'+'),
-        (egui::Key::OpenBracket, false) => Key::Char(b'['),
-        (egui::Key::OpenBracket, true) => Key::Char(b'{'),
-        (egui::Key::Minus, false) => Key::Char(b'-'),
-        (egui::Key::Minus, true) => Key::Char(b'_'),
-        (egui::Key::Period, false) => Key::Char(b'.'),
-        (egui::Key::Period, true) => Key::Char(b'>'),
-        (egui::Key::CloseBracket, false) => Key::Char(b']'),
-        (egui::Key::CloseBracket, true) => Key::Char(b'}'),
-        (egui::Key::Semicolon, _) => Key::Char(b';'),
-        (egui::Key::Colon, _) => Key::Char(b':'),
-        (egui::Key::Slash, _) => Key::Char(b'/'),
-        (egui::Key::Questionmark, _) => Key::Char(b'?'),
-        (egui::Key::Space, _) => Key::Char(b' '),
-        (egui::Key::Tab, _) => Key::Char(b'\t'),
-        (egui::Key::Enter, _) => Key::Char(b'\r'),
-        _ => return None,
-    };
-    Some(c)
-}
+mod keymap;
+mod journal;
+
+#[cfg(all(not(target_arch = "wasm32"), feature = "drm"))]
+mod drm;
 
 #[cfg_attr(target_arch = "wasm32", path = "web.rs")]
 #[cfg_attr(not(target_arch = "wasm32"), path = "native.rs")]
 mod core;
 
+/// Which windowing backend to run under.
+///
+/// `core::run` owns ROM loading and `Uxn`/`Varvara` construction; once
+/// that's done it's expected to call [`run_backend`], which is where the
+/// selected variant actually gets dispatched.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Backend {
+    /// The default `eframe`/`egui` windowed backend.
+    Eframe,
+    /// Headless KMS scanout + `libinput`, for running without a compositor.
+    #[cfg(all(not(target_arch = "wasm32"), feature = "drm"))]
+    Drm,
+}
+
+impl Backend {
+    /// Reads `RAVEN_BACKEND` (`"eframe"` or `"drm"`), defaulting to
+    /// `Eframe` if unset or unrecognized so raven still starts normally
+    /// when the variable isn't set.
+    fn from_env() -> Backend {
+        match std::env::var("RAVEN_BACKEND").as_deref() {
+            #[cfg(all(not(target_arch = "wasm32"), feature = "drm"))]
+            Ok("drm") => Backend::Drm,
+            Ok("eframe") | Ok(_) | Err(_) => Backend::Eframe,
+        }
+    }
+}
+
+/// Dispatches `vm`/`dev` to `backend`, having first built the keymap and
+/// optional input journal from the environment. `core::run` (in
+/// `native.rs`) owns ROM loading and `Uxn`/`Varvara` construction and is
+/// expected to call this once both are ready; like `RAVEN_BACKEND` above,
+/// env vars are the one configuration surface this crate controls outside
+/// of `core::run`'s own (unwired-in-this-tree) CLI parsing:
+///
+/// * `RAVEN_KEYMAP` selects a layout by name (see [`keymap::Keymap::named`]),
+///   defaulting to `"us"`.
+/// * `RAVEN_REPLAY=<path>` replays a previously recorded journal in place
+///   of live input; `RAVEN_RECORD=<path>` records this session to `path`
+///   instead. If both are set, `RAVEN_REPLAY` wins.
+///
+/// The keymap and journal are only handed to `Backend::Eframe`;
+/// `Backend::Drm` decodes keys with its own evdev table and neither
+/// records nor replays input.
+pub fn run_backend(
+    backend: Backend,
+    vm: Uxn<'static>,
+    dev: Varvara,
+    rom_hash: u64,
+) -> anyhow::Result<()> {
+    let keymap_name =
+        std::env::var("RAVEN_KEYMAP").unwrap_or_else(|_| "us".to_string());
+    let keymap = keymap::Keymap::named(&keymap_name);
+
+    let screen_size = dev.screen_size();
+    let journal = if let Ok(path) = std::env::var("RAVEN_REPLAY") {
+        Some(Stage::start_replay(
+            std::path::Path::new(&path),
+            rom_hash,
+            screen_size,
+        )?)
+    } else if let Ok(path) = std::env::var("RAVEN_RECORD") {
+        Some(Stage::start_recording(
+            std::path::PathBuf::from(path),
+            rom_hash,
+            screen_size,
+        ))
+    } else {
+        None
+    };
+
+    match backend {
+        Backend::Eframe => run_eframe(vm, dev, keymap, journal),
+        #[cfg(all(not(target_arch = "wasm32"), feature = "drm"))]
+        Backend::Drm => drm::run(vm, dev),
+    }
+}
+
+/// Launches the `eframe`/`egui` windowed loop with a freshly built [`Stage`].
+fn run_eframe(
+    vm: Uxn<'static>,
+    dev: Varvara,
+    keymap: keymap::Keymap,
+    journal: Option<JournalMode>,
+) -> anyhow::Result<()> {
+    eframe::run_native(
+        "raven",
+        eframe::NativeOptions::default(),
+        Box::new(move |cc| {
+            Ok(Box::new(Stage::new(vm, dev, &cc.egui_ctx, keymap, journal))
+                as Box<dyn eframe::App>)
+        }),
+    )
+    .map_err(|e| anyhow::anyhow!("eframe error: {e}"))
+}
+
 fn main() -> anyhow::Result<()> {
-    let out = core::run();
+    let backend = Backend::from_env();
+    let out = core::run(backend);
     match &out {
         Ok(()) => info!("core::run() completed successfully"),
         Err(e) => error!("core::run() failed: {e:?}"),